@@ -0,0 +1,379 @@
+//! DRM/KMS local preview sink
+//!
+//! Subscribes to camera frames from iceoryx2 and scans them out directly onto
+//! a KMS plane via atomic commits, no encoder and no FFmpeg involved. Useful
+//! for headless Pi setups with an attached display (HDMI or a DSI panel).
+//!
+//! # Usage
+//!
+//! Terminal 1: Start the publisher (main binary)
+//!   $ cargo run
+//!
+//! Terminal 2: Start this preview sink
+//!   $ cargo run --example kms_preview -- --card /dev/dri/card0
+//!
+//! Requires permission to open the DRM card (usually membership in the
+//! `video` group) and a connector that is not already driven by a compositor.
+
+use std::fs::{File, OpenOptions};
+use std::os::fd::{AsFd, BorrowedFd};
+
+use drm::buffer::{self, DrmFourcc};
+use drm::control::{
+    atomic, connector, crtc, framebuffer, plane, property, AddFbFlags, AtomicCommitFlags, Device as ControlDevice,
+    PlanarBuffer,
+};
+use drm::Device;
+
+use splitter::depad::{depad_nv12, depad_yuv420};
+use splitter::frame::{Frame, PixelFormat};
+use iceoryx2::prelude::*;
+
+const SERVICE_NAME: &str = "camera/0/frames";
+
+/// Triple-buffered scanout ring, mirroring the kmscapture `CAMERA_BUF_QUEUE_SIZE` pattern.
+const CAMERA_BUF_QUEUE_SIZE: usize = 3;
+
+struct Card(File);
+
+impl std::os::fd::AsFd for Card {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+impl Device for Card {}
+impl ControlDevice for Card {}
+
+/// One dumb-buffer scanout target in the ring.
+///
+/// All planes for a frame live in a single dumb buffer object (one GEM
+/// handle), laid out Y-then-chroma exactly like `depad_yuv420`/`depad_nv12`
+/// pack their output, so `write_dumb_buffer` can copy each plane straight
+/// from the depadded source into its own region of the mapping.
+struct ScanoutBuffer {
+    fb: framebuffer::Handle,
+    handle: drm::buffer::DumbBuffer,
+    width: u32,
+    height: u32,
+    /// Per-plane (pitch, offset, row count), in Y/U/V or Y/UV order.
+    planes: [(u32, u32, u32); 4],
+    plane_count: usize,
+    /// Total bytes the frame occupies across all planes.
+    total_len: usize,
+}
+
+/// Describes a [`ScanoutBuffer`]'s plane layout to `add_planar_framebuffer`,
+/// before the framebuffer handle it will produce exists.
+struct PlanarLayout {
+    handle: buffer::Handle,
+    width: u32,
+    height: u32,
+    fourcc: DrmFourcc,
+    planes: [(u32, u32, u32); 4],
+    plane_count: usize,
+}
+
+impl PlanarBuffer for PlanarLayout {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn format(&self) -> DrmFourcc {
+        self.fourcc
+    }
+
+    fn pitches(&self) -> [u32; 4] {
+        std::array::from_fn(|i| self.planes.get(i).map(|&(pitch, ..)| pitch).unwrap_or(0))
+    }
+
+    fn handles(&self) -> [Option<buffer::Handle>; 4] {
+        std::array::from_fn(|i| (i < self.plane_count).then_some(self.handle))
+    }
+
+    fn offsets(&self) -> [u32; 4] {
+        std::array::from_fn(|i| self.planes.get(i).map(|&(_, offset, _)| offset).unwrap_or(0))
+    }
+}
+
+fn pixel_format_to_fourcc(format: PixelFormat) -> Option<DrmFourcc> {
+    match format {
+        PixelFormat::Yuv420 => Some(DrmFourcc::Yuv420),
+        PixelFormat::Nv12 => Some(DrmFourcc::Nv12),
+        PixelFormat::Nv21 => Some(DrmFourcc::Nv21),
+        PixelFormat::Unknown => None,
+    }
+}
+
+/// Computes each plane's (pitch, offset, row count) within a single packed
+/// allocation, matching the layout `depad_yuv420`/`depad_nv12` produce:
+/// a full-resolution 8bpp Y plane followed by 4:2:0-subsampled chroma
+/// (two half-width/half-height planes for YUV420, one half-height
+/// full-width interleaved plane for NV12/NV21).
+fn plane_layout(fourcc: DrmFourcc, width: u32, height: u32) -> ([(u32, u32, u32); 4], usize, usize) {
+    let y_pitch = width;
+    let y_rows = height;
+    let y_len = (y_pitch * y_rows) as usize;
+
+    match fourcc {
+        DrmFourcc::Yuv420 => {
+            let c_pitch = width.div_ceil(2);
+            let c_rows = height.div_ceil(2);
+            let c_len = (c_pitch * c_rows) as usize;
+            let planes = [
+                (y_pitch, 0u32, y_rows),
+                (c_pitch, y_len as u32, c_rows),
+                (c_pitch, (y_len + c_len) as u32, c_rows),
+                (0, 0, 0),
+            ];
+            (planes, 3, y_len + 2 * c_len)
+        }
+        // NV12/NV21: one interleaved UV plane at full width, half height.
+        _ => {
+            let uv_rows = height.div_ceil(2);
+            let uv_len = (y_pitch * uv_rows) as usize;
+            let planes = [(y_pitch, 0u32, y_rows), (y_pitch, y_len as u32, uv_rows), (0, 0, 0), (0, 0, 0)];
+            (planes, 2, y_len + uv_len)
+        }
+    }
+}
+
+/// Finds the first connected connector, the first CRTC its encoder can
+/// drive, and the first plane that is unbound or already bound to that CRTC,
+/// and returns (connector, crtc, plane). Whether the CRTC is enabled, the
+/// plane's type (primary/overlay/cursor) and its possible-CRTCs mask are not
+/// checked.
+fn find_display_pipe(
+    card: &Card,
+) -> Result<(connector::Handle, crtc::Handle, plane::Handle), Box<dyn std::error::Error>> {
+    let res = card.resource_handles()?;
+
+    let conn = res
+        .connectors()
+        .iter()
+        .find_map(|&h| {
+            let info = card.get_connector(h, false).ok()?;
+            (info.state() == connector::State::Connected).then_some(h)
+        })
+        .ok_or("no connected display found")?;
+
+    let conn_info = card.get_connector(conn, false)?;
+    let encoder_handle = conn_info
+        .current_encoder()
+        .or_else(|| conn_info.encoders().first().copied())
+        .ok_or("connector has no usable encoder")?;
+    let encoder_info = card.get_encoder(encoder_handle)?;
+
+    let crtc = res
+        .filter_crtcs(encoder_info.possible_crtcs())
+        .first()
+        .copied()
+        .ok_or("no CRTC wired to this connector's encoder")?;
+
+    let plane = card
+        .plane_handles()?
+        .iter()
+        .find(|&&p| {
+            card.get_plane(p)
+                .map(|info| info.crtc().is_none() || info.crtc() == Some(crtc))
+                .unwrap_or(false)
+        })
+        .copied()
+        .ok_or("no usable plane found")?;
+
+    Ok((conn, crtc, plane))
+}
+
+fn alloc_scanout_ring(
+    card: &Card,
+    width: u32,
+    height: u32,
+    fourcc: DrmFourcc,
+) -> Result<Vec<ScanoutBuffer>, Box<dyn std::error::Error>> {
+    let (planes, plane_count, total_len) = plane_layout(fourcc, width, height);
+    // A dumb buffer is one flat allocation; request enough 8bpp rows at
+    // `width` to hold every plane packed one after another (Y then chroma),
+    // since `plane_layout` lays them out assuming that pitch.
+    let alloc_rows = (total_len as u32).div_ceil(width);
+
+    (0..CAMERA_BUF_QUEUE_SIZE)
+        .map(|_| {
+            let handle = card.create_dumb_buffer((width, alloc_rows), fourcc, 8)?;
+            let layout = PlanarLayout { handle: handle.handle(), width, height, fourcc, planes, plane_count };
+            let fb = card.add_planar_framebuffer(&layout, AddFbFlags::empty())?;
+            Ok(ScanoutBuffer { fb, handle, width, height, planes, plane_count, total_len })
+        })
+        .collect()
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    eprintln!("KMS preview starting...");
+
+    let mut card_path = "/dev/dri/card0".to_string();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--card" {
+            card_path = args.next().unwrap_or(card_path);
+        }
+    }
+
+    let file = OpenOptions::new().read(true).write(true).open(&card_path)?;
+    let card = Card(file);
+
+    let (conn, crtc_handle, plane_handle) = find_display_pipe(&card)?;
+    eprintln!("Using connector {conn:?}, crtc {crtc_handle:?}, plane {plane_handle:?}");
+
+    // Subscribe to camera frames
+    let node = NodeBuilder::new().create::<ipc::Service>()?;
+    let service = node
+        .service_builder(&SERVICE_NAME.try_into().unwrap())
+        .publish_subscribe::<Frame>()
+        .open()?;
+    let subscriber = service.subscriber_builder().create()?;
+
+    eprintln!("Subscribed to {}", SERVICE_NAME);
+
+    eprintln!("Waiting for first frame...");
+    let first_frame = loop {
+        if let Some(sample) = subscriber.receive()? {
+            break sample;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    };
+
+    let payload = first_frame.payload();
+    let width = payload.width;
+    let height = payload.height;
+    let stride = payload.stride;
+    let format = payload.format;
+
+    let fourcc = pixel_format_to_fourcc(format).ok_or("pixel format has no DRM fourcc mapping")?;
+    eprintln!("Got first frame: {width}x{height} (stride={stride}, format={format:?} -> {fourcc:?})");
+
+    let ring = alloc_scanout_ring(&card, width, height, fourcc)?;
+    let mut next_buf = 0usize;
+    let mut depadded = Vec::with_capacity((width * height * 3 / 2) as usize);
+    let needs_depad = stride != width;
+    let depad_fn: fn(&[u8], u32, u32, u32, &mut Vec<u8>) = match format {
+        PixelFormat::Yuv420 => depad_yuv420,
+        _ => depad_nv12,
+    };
+
+    let mut count = 0u64;
+    loop {
+        match subscriber.receive()? {
+            Some(sample) => {
+                let payload = sample.payload();
+                let packed: &[u8] = if needs_depad {
+                    depad_fn(&payload.data[..payload.len as usize], width, height, stride, &mut depadded);
+                    &depadded
+                } else {
+                    &payload.data[..payload.len as usize]
+                };
+
+                let buf = &ring[next_buf];
+                write_dumb_buffer(&card, buf, packed)?;
+                scanout(&card, conn, crtc_handle, plane_handle, buf)?;
+                next_buf = (next_buf + 1) % CAMERA_BUF_QUEUE_SIZE;
+
+                count += 1;
+                if count % 100 == 0 {
+                    eprintln!("Displayed {count} frames");
+                }
+            }
+            None => {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        }
+    }
+}
+
+/// Copies depadded pixel data into a dumb buffer's mmap'd backing memory.
+///
+/// `data` must already be packed Y-then-chroma with no row padding (as
+/// `depad_yuv420`/`depad_nv12` produce); it is an error, not a truncation,
+/// if its length differs from the size `alloc_scanout_ring` sized the buffer for.
+fn write_dumb_buffer(
+    card: &Card,
+    buf: &ScanoutBuffer,
+    data: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if data.len() != buf.total_len {
+        return Err(format!(
+            "frame data is {} bytes, scanout buffer expects exactly {}",
+            data.len(),
+            buf.total_len
+        )
+        .into());
+    }
+
+    let mut mapping = card.map_dumb_buffer(&buf.handle)?;
+    let dst = mapping.as_mut();
+    if dst.len() < buf.total_len {
+        return Err(format!("dumb buffer mapping is only {} bytes, need {}", dst.len(), buf.total_len).into());
+    }
+    dst[..buf.total_len].copy_from_slice(data);
+    Ok(())
+}
+
+/// Issues an atomic commit that points the plane at `buf`, scaled/positioned
+/// over the full CRTC via `CRTC_X/Y/W/H`, and flips on the next vblank.
+fn scanout(
+    card: &Card,
+    conn: connector::Handle,
+    crtc_handle: crtc::Handle,
+    plane_handle: plane::Handle,
+    buf: &ScanoutBuffer,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut req = atomic::AtomicModeReq::new();
+
+    // Stretch over the CRTC's current mode; native size if it has none.
+    let (crtc_w, crtc_h) = card
+        .get_crtc(crtc_handle)?
+        .mode()
+        .map(|m| (m.size().0 as u64, m.size().1 as u64))
+        .unwrap_or((buf.width as u64, buf.height as u64));
+
+    let props = card.get_properties(plane_handle)?;
+    for (name, value) in [
+        ("FB_ID", buf.fb.into()),
+        ("CRTC_ID", crtc_handle.into()),
+        ("SRC_X", 0),
+        ("SRC_Y", 0),
+        ("SRC_W", (buf.width as u64) << 16),
+        ("SRC_H", (buf.height as u64) << 16),
+        ("CRTC_X", 0),
+        ("CRTC_Y", 0),
+        ("CRTC_W", crtc_w),
+        ("CRTC_H", crtc_h),
+    ] {
+        if let Some(prop) = find_property(card, &props, name)? {
+            req.add_property(plane_handle, prop, property::Value::UnsignedRange(value));
+        }
+    }
+    let _ = conn;
+
+    // Binding a plane's CRTC_ID for the first time is a modeset-affecting
+    // change on most drivers (find_display_pipe deliberately accepts planes
+    // not yet bound to any CRTC), so ALLOW_MODESET is required or the first
+    // commit is rejected with EINVAL. This loop is synchronous and never
+    // reads the completion event back off the DRM fd, so PAGE_FLIP_EVENT is
+    // deliberately not requested here: asking for it without ever draining
+    // it would exhaust the fd's finite per-fd event queue after a few
+    // hundred frames and start failing every commit after that.
+    card.atomic_commit(AtomicCommitFlags::ALLOW_MODESET, req)?;
+    Ok(())
+}
+
+fn find_property(
+    card: &Card,
+    props: &drm::control::PropertyValueSet,
+    name: &str,
+) -> Result<Option<property::Handle>, Box<dyn std::error::Error>> {
+    for &(handle, _) in props.as_ref() {
+        let info = card.get_property(handle)?;
+        if info.name().to_str().unwrap_or_default() == name {
+            return Ok(Some(handle));
+        }
+    }
+    Ok(None)
+}
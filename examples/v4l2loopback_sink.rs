@@ -0,0 +1,178 @@
+//! V4L2 loopback output sink
+//!
+//! Subscribes to camera frames from iceoryx2 and re-publishes them as a
+//! standard `/dev/videoN` capture device, mirroring how libcamera's V4L2
+//! compatibility proxy bridges a non-V4L2 source into the V4L2 API. Any
+//! V4L2 consumer (OBS, Chrome/Firefox getUserMedia, `ffmpeg -f v4l2`) can
+//! then read the Pi camera without knowing anything about iceoryx2.
+//!
+//! # Prerequisites
+//!
+//! 1. Load v4l2loopback in output mode:
+//!    $ sudo modprobe v4l2loopback video_nr=10 card_label="picamfeedsplitter" exclusive_caps=1
+//!
+//! # Usage
+//!
+//! Terminal 1: Start the publisher (main binary)
+//!   $ cargo run
+//!
+//! Terminal 2: Start this sink
+//!   $ cargo run --example v4l2loopback_sink -- --device /dev/video10
+//!
+//! Terminal 3: Consume it like any other webcam
+//!   $ ffplay -f v4l2 /dev/video10
+
+use std::fs::OpenOptions;
+use std::os::fd::AsRawFd;
+
+use splitter::depad::{depad_nv12, depad_yuv420};
+use splitter::frame::{Frame, PixelFormat};
+use splitter::v4l2_output::{self, V4L2_PIX_FMT_NV12, V4L2_PIX_FMT_NV21, V4L2_PIX_FMT_YUV420};
+use iceoryx2::prelude::*;
+
+const SERVICE_NAME: &str = "camera/0/frames";
+
+fn pixel_format_to_v4l2(format: PixelFormat) -> u32 {
+    match format {
+        PixelFormat::Yuv420 => V4L2_PIX_FMT_YUV420,
+        PixelFormat::Nv12 => V4L2_PIX_FMT_NV12,
+        PixelFormat::Nv21 => V4L2_PIX_FMT_NV21,
+        PixelFormat::Unknown => V4L2_PIX_FMT_NV12,
+    }
+}
+
+/// Minimal v4l2loopback output-mode driver: negotiate the format once with
+/// `S_FMT`, then push depadded frames through `QBUF`/`DQBUF` on an mmap'd
+/// buffer. Real V4L2 output devices can request many buffers; v4l2loopback's
+/// output side works fine with the single persistent mmap buffer modeled here.
+struct LoopbackDevice {
+    file: std::fs::File,
+    width: u32,
+    height: u32,
+    bytesperline: u32,
+    buffer: memmap2::MmapMut,
+}
+
+impl LoopbackDevice {
+    fn open(path: &str, width: u32, height: u32, stride: u32, format: PixelFormat) -> std::io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let fd = file.as_raw_fd();
+
+        let fmt = v4l2_output::set_format(fd, width, height, stride, pixel_format_to_v4l2(format))
+            .map_err(std::io::Error::from)?;
+        v4l2_output::request_one_buffer(fd).map_err(std::io::Error::from)?;
+        let buf = v4l2_output::query_buffer(fd, 0).map_err(std::io::Error::from)?;
+
+        let mapping = unsafe {
+            memmap2::MmapOptions::new()
+                .len(buf.length as usize)
+                .offset(buf.mmap_offset() as u64)
+                .map_mut(&file)?
+        };
+
+        v4l2_output::stream_on(fd).map_err(std::io::Error::from)?;
+
+        Ok(Self {
+            file,
+            width,
+            height,
+            bytesperline: fmt.pix.bytesperline,
+            buffer: mapping,
+        })
+    }
+
+    fn write_frame(&mut self, packed: &[u8]) -> std::io::Result<()> {
+        let len = packed.len().min(self.buffer.len());
+        self.buffer[..len].copy_from_slice(&packed[..len]);
+
+        let fd = self.file.as_raw_fd();
+        v4l2_output::queue_and_dequeue(fd, 0, len as u32).map_err(std::io::Error::from)
+    }
+}
+
+impl Drop for LoopbackDevice {
+    fn drop(&mut self) {
+        if let Err(e) = v4l2_output::stream_off(self.file.as_raw_fd()) {
+            eprintln!("v4l2loopback stream off failed: {e}");
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    eprintln!("V4L2 loopback sink starting...");
+
+    let mut device_path = "/dev/video10".to_string();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--device" {
+            device_path = args.next().unwrap_or(device_path);
+        }
+    }
+
+    let node = NodeBuilder::new().create::<ipc::Service>()?;
+    let service = node
+        .service_builder(&SERVICE_NAME.try_into().unwrap())
+        .publish_subscribe::<Frame>()
+        .open()?;
+    let subscriber = service.subscriber_builder().create()?;
+
+    eprintln!("Subscribed to {}", SERVICE_NAME);
+
+    eprintln!("Waiting for first frame...");
+    let first_frame = loop {
+        if let Some(sample) = subscriber.receive()? {
+            break sample;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    };
+
+    let payload = first_frame.payload();
+    let width = payload.width;
+    let height = payload.height;
+    let stride = payload.stride;
+    let format = payload.format;
+
+    eprintln!("Got first frame: {width}x{height} (stride={stride}, format={format:?})");
+
+    let mut device = LoopbackDevice::open(&device_path, width, height, stride, format)?;
+    eprintln!("Opened {device_path} ({}x{}, bytesperline={})", device.width, device.height, device.bytesperline);
+
+    let mut depadded = Vec::with_capacity((width * height * 3 / 2) as usize);
+    let needs_depad = stride != width;
+    let depad_fn: fn(&[u8], u32, u32, u32, &mut Vec<u8>) = match format {
+        PixelFormat::Yuv420 => depad_yuv420,
+        _ => depad_nv12,
+    };
+
+    let write_one = |payload: &Frame, depadded: &mut Vec<u8>, device: &mut LoopbackDevice| -> std::io::Result<()> {
+        if needs_depad {
+            depad_fn(&payload.data[..payload.len as usize], width, height, stride, depadded);
+            device.write_frame(depadded)
+        } else {
+            device.write_frame(&payload.data[..payload.len as usize])
+        }
+    };
+
+    write_one(first_frame.payload(), &mut depadded, &mut device)?;
+
+    let mut count = 0u64;
+    loop {
+        match subscriber.receive()? {
+            Some(sample) => {
+                if let Err(e) = write_one(sample.payload(), &mut depadded, &mut device) {
+                    eprintln!("v4l2loopback write failed: {e}");
+                    break;
+                }
+                count += 1;
+                if count % 100 == 0 {
+                    eprintln!("Pushed {count} frames to {device_path}");
+                }
+            }
+            None => {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        }
+    }
+
+    Ok(())
+}
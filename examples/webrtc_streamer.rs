@@ -22,76 +22,151 @@
 //! Browser: Open http://<pi-ip>:8889/camera to view WebRTC stream
 
 use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
 use std::process::{Command, Stdio};
 
-use splitter::frame::{Frame, PixelFormat, MAX_FRAME_SIZE};
+use splitter::depad::{depad_nv12, depad_yuv420};
+use splitter::dmabuf;
+use splitter::frame::{Frame, FrameRef, PixelFormat, MAX_FRAME_SIZE, MAX_PLANES};
 use iceoryx2::prelude::*;
 
-const SERVICE_NAME: &str = "camera/frames";
+/// Resolves which camera's services to subscribe to from `--camera <index>`
+/// (selects `camera/<index>/frames[_ref]`, matching the publisher's default
+/// naming) or an explicit `--service <name>` override, plus the companion
+/// dmabuf socket path for zero-copy mode, which always follows `--camera`
+/// (an explicit `--service` does not change it).
+fn resolve_services() -> (String, String, String) {
+    let mut camera_index = 0usize;
+    let mut explicit_service: Option<String> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--camera" => camera_index = args.next().and_then(|s| s.parse().ok()).unwrap_or(camera_index),
+            "--service" => explicit_service = args.next(),
+            _ => {}
+        }
+    }
 
-/// Remove stride padding from YUV420 (I420) frame data.
-fn depad_yuv420(data: &[u8], width: u32, height: u32, stride: u32, out: &mut Vec<u8>) {
-    out.clear();
-    let w = width as usize;
-    let h = height as usize;
-    let s = stride as usize;
+    let frames = explicit_service
+        .clone()
+        .unwrap_or_else(|| format!("camera/{camera_index}/frames"));
+    let frames_ref = explicit_service
+        .map(|s| format!("{s}_ref"))
+        .unwrap_or_else(|| format!("camera/{camera_index}/frames_ref"));
+    let dmabuf_path = format!("/tmp/picamfeedsplitter-dmabuf-{camera_index}.sock");
+
+    (frames, frames_ref, dmabuf_path)
+}
 
-    // Y plane: height rows of stride bytes -> height rows of width bytes
-    let y_plane = &data[..s * h];
-    for row in 0..h {
-        out.extend_from_slice(&y_plane[row * s..row * s + w]);
+/// Maps a dmabuf fd read-only for the duration of one frame's use.
+///
+/// The fd arrives over the companion `SCM_RIGHTS` socket (see [`dmabuf`])
+/// rather than as part of the iceoryx2 payload; the matching [`FrameRef`]
+/// only describes its layout.
+struct MappedDmabuf {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl MappedDmabuf {
+    fn new(fd: std::os::fd::OwnedFd, offset: u32, len: usize) -> std::io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                offset as libc::off_t,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
     }
 
-    // U plane: height/2 rows of stride/2 bytes -> height/2 rows of width/2 bytes
-    let u_offset = s * h;
-    let u_plane = &data[u_offset..u_offset + (s / 2) * (h / 2)];
-    for row in 0..(h / 2) {
-        out.extend_from_slice(&u_plane[row * (s / 2)..row * (s / 2) + (w / 2)]);
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
     }
+}
 
-    // V plane: height/2 rows of stride/2 bytes -> height/2 rows of width/2 bytes
-    let v_offset = u_offset + (s / 2) * (h / 2);
-    let v_plane = &data[v_offset..v_offset + (s / 2) * (h / 2)];
-    for row in 0..(h / 2) {
-        out.extend_from_slice(&v_plane[row * (s / 2)..row * (s / 2) + (w / 2)]);
+impl Drop for MappedDmabuf {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
     }
 }
 
-/// Remove stride padding from NV12 frame data.
-fn depad_nv12(data: &[u8], width: u32, height: u32, stride: u32, out: &mut Vec<u8>) {
-    out.clear();
-    let w = width as usize;
-    let h = height as usize;
-    let s = stride as usize;
-
-    // Y plane: height rows of stride bytes -> height rows of width bytes
-    let y_plane = &data[..s * h];
-    for row in 0..h {
-        out.extend_from_slice(&y_plane[row * s..row * s + w]);
+/// Maps a frame's planes as a single contiguous view.
+///
+/// The publisher sends one fd per plane (`FrameRef::plane_offsets` is each
+/// plane's real offset into *its own* dmabuf), since planes aren't
+/// guaranteed to share a single backing allocation. This streamer's depad
+/// path needs one flat `&[u8]` for the whole frame, so it only supports the
+/// case where every plane fd actually does refer to the same dmabuf and the
+/// plane offsets are laid out back-to-back; anything else is rejected
+/// outright rather than silently read from the wrong region.
+fn map_contiguous_frame(
+    fds: Vec<std::os::fd::OwnedFd>,
+    frame_ref: &FrameRef,
+) -> std::io::Result<MappedDmabuf> {
+    let plane_count = frame_ref.plane_count as usize;
+    if fds.len() != plane_count || plane_count == 0 {
+        return Err(std::io::Error::other(format!(
+            "expected {plane_count} dmabuf fd(s) for this frame, got {}",
+            fds.len()
+        )));
     }
 
-    // UV plane (interleaved): height/2 rows of stride bytes -> height/2 rows of width bytes
-    let uv_offset = s * h;
-    let uv_plane = &data[uv_offset..uv_offset + s * (h / 2)];
-    for row in 0..(h / 2) {
-        out.extend_from_slice(&uv_plane[row * s..row * s + w]);
+    let first_stat = nix::sys::stat::fstat(&fds[0])?;
+    for fd in &fds[1..] {
+        let stat = nix::sys::stat::fstat(fd)?;
+        if (stat.st_dev, stat.st_ino) != (first_stat.st_dev, first_stat.st_ino) {
+            return Err(std::io::Error::other(
+                "frame planes are backed by separate dmabufs; this streamer only supports a single contiguous dmabuf per frame",
+            ));
+        }
     }
+
+    let mut expected_offset = frame_ref.plane_offsets[0];
+    let mut total_len = 0usize;
+    for i in 0..plane_count {
+        if frame_ref.plane_offsets[i] != expected_offset {
+            return Err(std::io::Error::other(
+                "frame planes are not laid out contiguously; this streamer only supports a single contiguous dmabuf per frame",
+            ));
+        }
+        expected_offset += frame_ref.plane_lens[i];
+        total_len += frame_ref.plane_lens[i] as usize;
+    }
+
+    MappedDmabuf::new(fds.into_iter().next().unwrap(), frame_ref.plane_offsets[0], total_len)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let zero_copy = std::env::args().skip(1).any(|a| a == "--zero-copy");
+    let (service_name, service_name_ref, dmabuf_path) = resolve_services();
+
+    if zero_copy {
+        return run_zero_copy(&service_name_ref, &dmabuf_path);
+    }
+
     eprintln!("WebRTC streamer starting...");
 
     // Subscribe to camera frames
     let node = NodeBuilder::new().create::<ipc::Service>()?;
 
     let service = node
-        .service_builder(&SERVICE_NAME.try_into().unwrap())
+        .service_builder(&service_name.as_str().try_into().unwrap())
         .publish_subscribe::<Frame>()
         .open()?;
 
     let subscriber = service.subscriber_builder().create()?;
 
-    eprintln!("Subscribed to {}", SERVICE_NAME);
+    eprintln!("Subscribed to {}", service_name);
 
     // Wait for first frame to get dimensions
     eprintln!("Waiting for first frame...");
@@ -197,3 +272,151 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     ffmpeg.wait()?;
     Ok(())
 }
+
+/// Zero-copy receive path: subscribes to the camera's `frames_ref` service
+/// for layout and connects to the companion dmabuf socket for the fd
+/// itself, mmap'ing each frame's dmabuf directly instead of reading a
+/// copied payload.
+fn run_zero_copy(service_name_ref: &str, dmabuf_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    eprintln!("WebRTC streamer starting (zero-copy)...");
+
+    let node = NodeBuilder::new().create::<ipc::Service>()?;
+
+    let service = node
+        .service_builder(&service_name_ref.try_into().unwrap())
+        .publish_subscribe::<FrameRef>()
+        .open()?;
+
+    let subscriber = service.subscriber_builder().create()?;
+
+    eprintln!("Subscribed to {}", service_name_ref);
+
+    let dmabuf_sock = UnixStream::connect(dmabuf_path)?;
+    eprintln!("Connected to dmabuf socket at {}", dmabuf_path);
+
+    eprintln!("Waiting for first frame...");
+    let first_ref = loop {
+        if let Some(sample) = subscriber.receive()? {
+            break *sample.payload();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    };
+
+    let width = first_ref.width;
+    let height = first_ref.height;
+    let stride = first_ref.stride;
+    let format = first_ref.format;
+
+    let ffmpeg_pix_fmt = match format {
+        PixelFormat::Yuv420 => "yuv420p",
+        PixelFormat::Nv12 => "nv12",
+        PixelFormat::Nv21 => "nv21",
+        PixelFormat::Unknown => {
+            eprintln!("Unknown pixel format, assuming nv12");
+            "nv12"
+        }
+    };
+
+    eprintln!("Got first frame: {}x{} (stride={}, format={:?}/{})", width, height, stride, format, ffmpeg_pix_fmt);
+
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args([
+            "-f", "rawvideo",
+            "-pix_fmt", ffmpeg_pix_fmt,
+            "-s", &format!("{}x{}", width, height),
+            "-r", "30",
+            "-i", "-",
+            "-c:v", "libx264",
+            "-preset", "ultrafast",
+            "-tune", "zerolatency",
+            "-g", "30",
+            "-f", "rtsp",
+            "-rtsp_transport", "tcp",
+            "rtsp://127.0.0.1:8554/camera",
+        ])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+
+    let mut stdin = ffmpeg.stdin.take().expect("Failed to open FFmpeg stdin");
+
+    eprintln!("FFmpeg started, streaming to rtsp://127.0.0.1:8554/camera");
+
+    let mut depadded = Vec::with_capacity((width * height * 3 / 2) as usize);
+    let needs_depad = stride != width;
+    let depad_fn: fn(&[u8], u32, u32, u32, &mut Vec<u8>) = match format {
+        PixelFormat::Yuv420 => depad_yuv420,
+        _ => depad_nv12,
+    };
+
+    let write_frame = |frame_ref: &FrameRef,
+                       data: &[u8],
+                       stdin: &mut std::process::ChildStdin,
+                       depadded: &mut Vec<u8>|
+     -> std::io::Result<()> {
+        if needs_depad {
+            depad_fn(data, frame_ref.width, frame_ref.height, frame_ref.stride, depadded);
+            stdin.write_all(depadded)
+        } else {
+            stdin.write_all(data)
+        }
+    };
+
+    let (first_seq, first_fds) = dmabuf::recv_fds(&dmabuf_sock, MAX_PLANES)?
+        .ok_or("dmabuf socket closed before first frame")?;
+    debug_assert_eq!(first_seq, first_ref.sequence);
+    let mapped = map_contiguous_frame(first_fds, &first_ref)?;
+    write_frame(&first_ref, mapped.as_slice(), &mut stdin, &mut depadded)?;
+    drop(mapped);
+    dmabuf::send_ack(&dmabuf_sock, first_seq)?;
+
+    let mut count = 0u64;
+    loop {
+        match subscriber.receive()? {
+            Some(sample) => {
+                let frame_ref = *sample.payload();
+                let (seq, fds) = match dmabuf::recv_fds(&dmabuf_sock, MAX_PLANES)? {
+                    Some(v) => v,
+                    None => {
+                        eprintln!("dmabuf socket closed");
+                        break;
+                    }
+                };
+                if seq != frame_ref.sequence {
+                    warn_mismatch(seq, frame_ref.sequence);
+                    continue;
+                }
+                let mapped = map_contiguous_frame(fds, &frame_ref)?;
+                let write_result = write_frame(&frame_ref, mapped.as_slice(), &mut stdin, &mut depadded);
+                drop(mapped);
+                // Ack even on a write error: the dmabuf read itself finished
+                // either way, and the producer is waiting on this ack to
+                // safely recycle the buffer regardless of what FFmpeg did
+                // with the data.
+                if let Err(e) = dmabuf::send_ack(&dmabuf_sock, seq) {
+                    eprintln!("dmabuf ack send failed: {}", e);
+                    break;
+                }
+                if let Err(e) = write_result {
+                    eprintln!("FFmpeg pipe closed: {}", e);
+                    break;
+                }
+                count += 1;
+                if count % 100 == 0 {
+                    eprintln!("Streamed {} frames (zero-copy)", count);
+                }
+            }
+            None => {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        }
+    }
+
+    drop(stdin);
+    ffmpeg.wait()?;
+    Ok(())
+}
+
+fn warn_mismatch(got_seq: u64, expected_seq: u64) {
+    eprintln!("dmabuf fd sequence {} does not match FrameRef sequence {}, dropping frame", got_seq, expected_seq);
+}
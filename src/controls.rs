@@ -0,0 +1,37 @@
+use iceoryx2::prelude::ZeroCopySend;
+
+/// Wire identifier for a sensor control accepted by the per-camera
+/// `camera/<index>/controls` service.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ControlId {
+    ExposureTime = 0,
+    AnalogueGain = 1,
+}
+
+impl ControlId {
+    pub fn from_wire(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(Self::ExposureTime),
+            1 => Some(Self::AnalogueGain),
+            _ => None,
+        }
+    }
+}
+
+/// A single control update pushed over the `camera/<index>/controls` iceoryx2 service.
+///
+/// `value` is reinterpreted according to `control_id`: an integer control
+/// like exposure time is carried in its bit pattern, a float control like
+/// analogue gain is carried as-is. See [`crate::delayed_controls`] for how
+/// updates published here get applied with the correct frame latency.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ZeroCopySend)]
+#[type_name("ControlUpdate")]
+pub struct ControlUpdate {
+    pub control_id: u32,
+    pub value: f32,
+}
+
+unsafe impl Send for ControlUpdate {}
+unsafe impl Sync for ControlUpdate {}
@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::controls::ControlId;
+
+/// How many frames (by sequence) of history to retain per control before
+/// pruning: entries active more than this many frames before the queried
+/// sequence are dropped.
+const HISTORY_DEPTH: u64 = 32;
+
+/// A control value tagged with the numeric type libcamera expects for it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlValue {
+    ExposureTime(i32),
+    AnalogueGain(f32),
+}
+
+impl ControlValue {
+    pub fn id(&self) -> ControlId {
+        match self {
+            ControlValue::ExposureTime(_) => ControlId::ExposureTime,
+            ControlValue::AnalogueGain(_) => ControlId::AnalogueGain,
+        }
+    }
+}
+
+/// Models libcamera's rkisp1 delayed-controls handling: a control written
+/// into a queued request does not take effect on the very next completed
+/// frame, but `latency` frames later. Tracking "the current setting" is not
+/// enough to describe what a completed frame actually captured with, so
+/// every write is stamped with the sequence it becomes active at and kept
+/// around until a frame with that sequence (or later) asks for it.
+pub struct DelayedControls {
+    latencies: HashMap<ControlId, u64>,
+    pending: HashMap<ControlId, ControlValue>,
+    history: HashMap<ControlId, BTreeMap<u64, ControlValue>>,
+}
+
+impl DelayedControls {
+    pub fn new(latencies: HashMap<ControlId, u64>) -> Self {
+        Self {
+            latencies,
+            pending: HashMap::new(),
+            history: HashMap::new(),
+        }
+    }
+
+    /// Records externally-pushed updates; the most recent value per control wins.
+    pub fn push(&mut self, updates: impl IntoIterator<Item = ControlValue>) {
+        for value in updates {
+            self.pending.insert(value.id(), value);
+        }
+    }
+
+    /// Whether a manual value has ever been pushed for any control. libcamera
+    /// ignores manually-written `ExposureTime`/`AnalogueGain` while `AeEnable`
+    /// is true, so callers use this to know when auto-exposure needs to be
+    /// switched off for manual control to have any effect.
+    pub fn has_manual_controls(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Returns the control values to write into the request about to be
+    /// queued for `next_sequence`, and records the sequence each becomes
+    /// active at so [`Self::applied_at`] can find it later.
+    pub fn controls_for_request(&mut self, next_sequence: u64) -> Vec<ControlValue> {
+        let mut out = Vec::new();
+        for (&id, &value) in &self.pending {
+            let latency = *self.latencies.get(&id).unwrap_or(&0);
+            let effective_at = next_sequence + latency;
+            self.history.entry(id).or_default().insert(effective_at, value);
+            out.push(value);
+        }
+        out
+    }
+
+    /// Looks up the value actually in effect for a completed frame's sequence,
+    /// then prunes entries active more than [`HISTORY_DEPTH`] frames before
+    /// `sequence`. A value not rewritten by [`Self::controls_for_request`]
+    /// within that window is pruned too, and later lookups return `None`.
+    pub fn applied_at(&mut self, id: ControlId, sequence: u64) -> Option<ControlValue> {
+        let hist = self.history.get_mut(&id)?;
+        let value = hist.range(..=sequence).next_back().map(|(_, v)| *v);
+        let cutoff = sequence.saturating_sub(HISTORY_DEPTH);
+        hist.retain(|&k, _| k >= cutoff);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latencies() -> HashMap<ControlId, u64> {
+        HashMap::from([(ControlId::ExposureTime, 2), (ControlId::AnalogueGain, 2)])
+    }
+
+    #[test]
+    fn applied_at_is_none_before_the_value_takes_effect() {
+        let mut delayed = DelayedControls::new(latencies());
+        delayed.push([ControlValue::ExposureTime(5000)]);
+        assert_eq!(delayed.controls_for_request(10), vec![ControlValue::ExposureTime(5000)]);
+
+        // Queued for request 10 with latency 2 -> active at sequence 12.
+        assert_eq!(delayed.applied_at(ControlId::ExposureTime, 11), None);
+        assert_eq!(
+            delayed.applied_at(ControlId::ExposureTime, 12),
+            Some(ControlValue::ExposureTime(5000))
+        );
+        // Stays in effect for later frames too, until superseded.
+        assert_eq!(
+            delayed.applied_at(ControlId::ExposureTime, 20),
+            Some(ControlValue::ExposureTime(5000))
+        );
+    }
+
+    #[test]
+    fn later_write_supersedes_earlier_one_once_active() {
+        let mut delayed = DelayedControls::new(latencies());
+        delayed.push([ControlValue::AnalogueGain(1.0)]);
+        delayed.controls_for_request(0); // active at sequence 2
+
+        delayed.push([ControlValue::AnalogueGain(2.0)]);
+        delayed.controls_for_request(5); // active at sequence 7
+
+        assert_eq!(
+            delayed.applied_at(ControlId::AnalogueGain, 6),
+            Some(ControlValue::AnalogueGain(1.0))
+        );
+        assert_eq!(
+            delayed.applied_at(ControlId::AnalogueGain, 7),
+            Some(ControlValue::AnalogueGain(2.0))
+        );
+    }
+
+    #[test]
+    fn history_is_pruned_beyond_history_depth() {
+        let mut delayed = DelayedControls::new(latencies());
+        delayed.push([ControlValue::ExposureTime(1000)]);
+        delayed.controls_for_request(0); // active at sequence 2
+
+        // Each call prunes based on the sequence just queried, so the entry
+        // survives until a query pushes the cutoff past it, not before.
+        assert_eq!(
+            delayed.applied_at(ControlId::ExposureTime, 2 + HISTORY_DEPTH),
+            Some(ControlValue::ExposureTime(1000))
+        );
+        assert_eq!(
+            delayed.applied_at(ControlId::ExposureTime, 2 + HISTORY_DEPTH + 1),
+            Some(ControlValue::ExposureTime(1000))
+        );
+        assert_eq!(delayed.applied_at(ControlId::ExposureTime, 2 + HISTORY_DEPTH + 2), None);
+    }
+
+    #[test]
+    fn has_manual_controls_reflects_whether_anything_was_ever_pushed() {
+        let mut delayed = DelayedControls::new(latencies());
+        assert!(!delayed.has_manual_controls());
+        delayed.push([ControlValue::ExposureTime(1000)]);
+        assert!(delayed.has_manual_controls());
+    }
+}
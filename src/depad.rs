@@ -0,0 +1,51 @@
+//! Stride-depadding helpers shared by consumers that need tightly packed
+//! YUV rather than the row-padded layout libcamera hands back.
+
+/// Remove stride padding from YUV420 (I420) frame data.
+pub fn depad_yuv420(data: &[u8], width: u32, height: u32, stride: u32, out: &mut Vec<u8>) {
+    out.clear();
+    let w = width as usize;
+    let h = height as usize;
+    let s = stride as usize;
+
+    // Y plane: height rows of stride bytes -> height rows of width bytes
+    let y_plane = &data[..s * h];
+    for row in 0..h {
+        out.extend_from_slice(&y_plane[row * s..row * s + w]);
+    }
+
+    // U plane: height/2 rows of stride/2 bytes -> height/2 rows of width/2 bytes
+    let u_offset = s * h;
+    let u_plane = &data[u_offset..u_offset + (s / 2) * (h / 2)];
+    for row in 0..(h / 2) {
+        out.extend_from_slice(&u_plane[row * (s / 2)..row * (s / 2) + (w / 2)]);
+    }
+
+    // V plane: height/2 rows of stride/2 bytes -> height/2 rows of width/2 bytes
+    let v_offset = u_offset + (s / 2) * (h / 2);
+    let v_plane = &data[v_offset..v_offset + (s / 2) * (h / 2)];
+    for row in 0..(h / 2) {
+        out.extend_from_slice(&v_plane[row * (s / 2)..row * (s / 2) + (w / 2)]);
+    }
+}
+
+/// Remove stride padding from NV12 frame data.
+pub fn depad_nv12(data: &[u8], width: u32, height: u32, stride: u32, out: &mut Vec<u8>) {
+    out.clear();
+    let w = width as usize;
+    let h = height as usize;
+    let s = stride as usize;
+
+    // Y plane: height rows of stride bytes -> height rows of width bytes
+    let y_plane = &data[..s * h];
+    for row in 0..h {
+        out.extend_from_slice(&y_plane[row * s..row * s + w]);
+    }
+
+    // UV plane (interleaved): height/2 rows of stride bytes -> height/2 rows of width bytes
+    let uv_offset = s * h;
+    let uv_plane = &data[uv_offset..uv_offset + s * (h / 2)];
+    for row in 0..(h / 2) {
+        out.extend_from_slice(&uv_plane[row * s..row * s + w]);
+    }
+}
@@ -0,0 +1,102 @@
+//! Out-of-band dmabuf file descriptor transport.
+//!
+//! iceoryx2 payloads live in shared memory and cannot carry a file
+//! descriptor, so a dmabuf fd exported from a `FrameBuffer` (the
+//! `VIDIOC_EXPBUF` pattern) has to ride a companion Unix domain socket
+//! using `SCM_RIGHTS` instead. This module is the minimal sender/receiver
+//! pair for that: one message per frame carrying that frame's plane fds
+//! (in plane order), tagged with the frame sequence so
+//! the receiver can match it back up to the `FrameRef` it got over iox2.
+
+use std::io;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// Sends a dmabuf fd plus its owning frame sequence number to a connected peer.
+pub fn send_fd(stream: &UnixStream, sequence: u64, fd: RawFd) -> io::Result<()> {
+    send_fds(stream, sequence, &[fd])
+}
+
+/// Sends multiple plane fds (in plane order) for a single frame sequence.
+pub fn send_fds(stream: &UnixStream, sequence: u64, fds: &[RawFd]) -> io::Result<()> {
+    use nix::sys::socket::{sendmsg, ControlMessage, MsgFlags};
+    use std::io::IoSlice;
+
+    let seq_bytes = sequence.to_le_bytes();
+    let iov = [IoSlice::new(&seq_bytes)];
+    let cmsg = [ControlMessage::ScmRights(fds)];
+    sendmsg::<()>(stream.as_raw_fd(), &iov, &cmsg, MsgFlags::empty(), None)
+        .map_err(io::Error::from)?;
+    Ok(())
+}
+
+/// Receives a dmabuf fd plus the frame sequence it belongs to. Returns
+/// `Ok(None)` on a clean peer disconnect.
+pub fn recv_fd(stream: &UnixStream) -> io::Result<Option<(u64, OwnedFd)>> {
+    Ok(recv_fds(stream, 1)?.map(|(seq, mut fds)| (seq, fds.remove(0))))
+}
+
+/// Receives up to `max_fds` plane fds for one frame sequence.
+pub fn recv_fds(stream: &UnixStream, max_fds: usize) -> io::Result<Option<(u64, Vec<OwnedFd>)>> {
+    use nix::sys::socket::{recvmsg, ControlMessageOwned, MsgFlags};
+    use std::io::IoSliceMut;
+
+    let mut seq_bytes = [0u8; 8];
+    let mut iov = [IoSliceMut::new(&mut seq_bytes)];
+    let mut cmsg_buf = nix::cmsg_space!([RawFd; 4]);
+
+    let msg = recvmsg::<()>(
+        stream.as_raw_fd(),
+        &mut iov,
+        Some(&mut cmsg_buf),
+        MsgFlags::empty(),
+    )
+    .map_err(io::Error::from)?;
+
+    if msg.bytes == 0 {
+        return Ok(None);
+    }
+
+    let mut fds = Vec::with_capacity(max_fds);
+    for cmsg in msg.cmsgs().map_err(|e| io::Error::other(e.to_string()))? {
+        if let ControlMessageOwned::ScmRights(received) = cmsg {
+            fds.extend(received.into_iter().map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }));
+        }
+    }
+    // Anything past `max_fds` is closed on drop rather than handed back.
+    fds.truncate(max_fds);
+
+    Ok(Some((u64::from_le_bytes(seq_bytes), fds)))
+}
+
+/// Acks that a consumer is done reading the dmabuf(s) for `sequence`, so the
+/// producer knows it's safe to requeue that buffer with libcamera without
+/// tearing a frame the consumer might still be mmap'd into.
+pub fn send_ack(stream: &UnixStream, sequence: u64) -> io::Result<()> {
+    use std::io::Write;
+    (&*stream).write_all(&sequence.to_le_bytes())
+}
+
+/// Receives one ack, returning the sequence it's for. Returns `Ok(None)` on
+/// a clean peer disconnect, `Err` on timeout (the stream's read timeout, if
+/// any) or other I/O error.
+pub fn recv_ack(stream: &UnixStream) -> io::Result<Option<u64>> {
+    use std::io::Read;
+    let mut seq_bytes = [0u8; 8];
+    match (&*stream).read_exact(&mut seq_bytes) {
+        Ok(()) => Ok(Some(u64::from_le_bytes(seq_bytes))),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Binds the companion socket, removing a stale file left by a previous run.
+pub fn bind_listener(path: &str) -> io::Result<UnixListener> {
+    let _ = std::fs::remove_file(path);
+    UnixListener::bind(path)
+}
+
+/// Borrows a fd without taking ownership, for passing to `mmap`/ioctl calls.
+pub fn borrow(fd: RawFd) -> BorrowedFd<'static> {
+    unsafe { BorrowedFd::borrow_raw(fd) }
+}
@@ -39,3 +39,38 @@ pub struct Frame {
 
 unsafe impl Send for Frame {}
 unsafe impl Sync for Frame {}
+
+/// Max number of planes a `FrameRef` can describe (Y/U/V or Y/UV, with headroom).
+pub const MAX_PLANES: usize = 4;
+
+/// Slim, zero-copy counterpart to [`Frame`].
+///
+/// Carries frame layout only; the actual pixel data stays in the dmabuf
+/// backing the originating `FrameBuffer`. The fd itself cannot travel in
+/// iceoryx2 shared memory, so it is handed to the consumer out-of-band
+/// over the companion Unix domain socket in [`crate::dmabuf`] (`SCM_RIGHTS`),
+/// keyed by `sequence`. Consumers that cannot import a dmabuf should use
+/// the copy-based [`Frame`] instead.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ZeroCopySend)]
+#[type_name("FrameRef")]
+pub struct FrameRef {
+    pub timestamp_ns: u64,
+    pub sequence: u64,
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32,
+    pub format: PixelFormat,
+    pub plane_count: u32,
+    /// Each plane's real byte offset into *its own* dmabuf, i.e. the same
+    /// `offset` libcamera's `FrameBuffer::Plane` reports (and `VIDIOC_EXPBUF`
+    /// would for a V4L2-backed buffer). Planes are not guaranteed to share a
+    /// single contiguous dmabuf; consumers must pair each offset/length with
+    /// the fd received at the same index over the companion socket, not
+    /// assume offset 0 of one shared fd.
+    pub plane_offsets: [u32; MAX_PLANES],
+    pub plane_lens: [u32; MAX_PLANES],
+}
+
+unsafe impl Send for FrameRef {}
+unsafe impl Sync for FrameRef {}
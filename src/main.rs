@@ -1,16 +1,90 @@
+mod controls;
+mod delayed_controls;
+mod depad;
+mod dmabuf;
 mod frame;
+mod metadata;
+mod v4l2_output;
 
+use std::collections::HashMap;
+use std::os::fd::AsRawFd;
 use std::sync::mpsc;
-use frame::{Frame, PixelFormat, MAX_FRAME_SIZE};
+use std::sync::{Arc, Mutex};
+use std::os::unix::net::UnixStream;
+use controls::{ControlId, ControlUpdate};
+use delayed_controls::{ControlValue, DelayedControls};
+use frame::{Frame, FrameRef, PixelFormat, MAX_FRAME_SIZE, MAX_PLANES};
 use iceoryx2::prelude::*;
 use libcamera::{
     controls::*,
     camera::{CameraConfigurationStatus, SensorConfiguration}, camera_manager::CameraManager, framebuffer::AsFrameBuffer, framebuffer_allocator::{FrameBuffer, FrameBufferAllocator}, framebuffer_map::MemoryMappedFrameBuffer, geometry::Size, request::ReuseFlag, stream::StreamRole
 };
+use metadata::FrameMetadata;
 use thiserror::Error;
 use tracing::{debug, error, info, warn};
 
-const SERVICE_NAME: &str = "camera/frames"; // iox2 service name
+/// Per-camera iceoryx2 service names are `camera/<index>/...`, e.g.
+/// `camera/0/frames`, so multiple cameras can publish side by side.
+fn service_name(camera_index: usize, suffix: &str) -> String {
+    format!("camera/{camera_index}/{suffix}")
+}
+
+/// Per-camera companion dmabuf socket path, parallel to `service_name`.
+fn dmabuf_socket_path(camera_index: usize) -> String {
+    format!("/tmp/picamfeedsplitter-dmabuf-{camera_index}.sock")
+}
+
+/// How long the producer waits for a zero-copy consumer to ack a frame
+/// before giving up on it and dropping the connection. Without this, a
+/// consumer that's still mmap'ing/reading a dmabuf when libcamera recaptures
+/// into it would see a torn frame; a consumer that never acks (stalled or
+/// dead) would otherwise stall the capture loop forever instead of just
+/// losing its own connection.
+const DMABUF_ACK_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// A camera to run a capture pipeline on, as selected by `--camera`.
+enum CameraSelector {
+    Index(usize),
+    Id(String),
+}
+
+fn parse_camera_selector(arg: &str) -> CameraSelector {
+    match arg.parse::<usize>() {
+        Ok(i) => CameraSelector::Index(i),
+        Err(_) => CameraSelector::Id(arg.to_string()),
+    }
+}
+
+/// Resolves a `--camera` selector to a concrete index by enumerating once
+/// up front; the pipeline thread itself re-enumerates to acquire the camera.
+fn resolve_camera_selector(selector: CameraSelector) -> Result<usize, Error> {
+    let mgr = CameraManager::new().map_err(|e| Error::Camera(format!("{e:?}")))?;
+    let cameras = mgr.cameras();
+    match selector {
+        CameraSelector::Index(i) => {
+            if i < cameras.len() {
+                Ok(i)
+            } else {
+                Err(Error::NoCamera)
+            }
+        }
+        CameraSelector::Id(id) => (0..cameras.len())
+            .find(|&i| cameras.get(i).map(|c| c.id() == id).unwrap_or(false))
+            .ok_or(Error::NoCamera),
+    }
+}
+
+/// Number of cameras currently enumerated by libcamera.
+fn count_cameras() -> Result<usize, Error> {
+    let mgr = CameraManager::new().map_err(|e| Error::Camera(format!("{e:?}")))?;
+    Ok(mgr.cameras().len())
+}
+
+/// Write-to-apply latency, in frames, for each delayed control. Matches the
+/// rkisp1 defaults libcamera ships for these two controls.
+fn control_latencies() -> HashMap<ControlId, u64> {
+    HashMap::from([(ControlId::ExposureTime, 2), (ControlId::AnalogueGain, 2)])
+}
 
 #[derive(Error, Debug)]
 enum Error {
@@ -29,23 +103,81 @@ fn main() -> Result<(), Error> {
 
     let mut width: u32 = 1280;
     let mut height: u32 = 720;
+    let mut zero_copy = false;
+    let mut camera_selector = CameraSelector::Index(0);
+    let mut run_all = false;
     let mut args = std::env::args().skip(1);
     while let Some(arg) = args.next() {
         match arg.as_str() {
             "--width" => width = args.next().and_then(|s| s.parse().ok()).unwrap_or(width),
             "--height" => height = args.next().and_then(|s| s.parse().ok()).unwrap_or(height),
+            "--zero-copy" => zero_copy = true,
+            "--camera" => {
+                if let Some(value) = args.next() {
+                    camera_selector = parse_camera_selector(&value);
+                }
+            }
+            "--all" => run_all = true,
             _ => {}
         }
     }
 
-    info!(width, height, "Starting camera publisher");
+    if run_all {
+        let count = count_cameras()?;
+        if count == 0 {
+            return Err(Error::NoCamera);
+        }
+        info!(count, "Running every attached camera");
+
+        // Each pipeline thread only returns on error (the capture loop is
+        // otherwise infinite), so joining sequentially would block forever
+        // on whichever camera happens to come first in `handles` while a
+        // failure in any other camera's thread goes unreported for the life
+        // of the process. Instead, every thread reports its own exit over
+        // `result_tx` and main returns as soon as the first one does.
+        let (result_tx, result_rx) = mpsc::channel();
+        let _handles: Vec<_> = (0..count)
+            .map(|camera_index| {
+                let result_tx = result_tx.clone();
+                std::thread::spawn(move || {
+                    // catch_unwind so a panicking pipeline still reports in
+                    // rather than leaving `result_tx` dropped silently and
+                    // main waiting forever on the other, healthy cameras.
+                    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        run_camera_pipeline(camera_index, width, height, zero_copy)
+                    }))
+                    .unwrap_or_else(|_| Err(Error::Camera(format!("camera {camera_index} pipeline thread panicked"))));
+                    let _ = result_tx.send((camera_index, result));
+                })
+            })
+            .collect();
+        drop(result_tx);
+
+        let (failed_index, result) = result_rx
+            .recv()
+            .expect("all camera pipeline threads disconnected without reporting");
+        error!(camera_index = failed_index, "Camera pipeline exited");
+        return result;
+    }
+
+    let camera_index = resolve_camera_selector(camera_selector)?;
+    run_camera_pipeline(camera_index, width, height, zero_copy)
+}
+
+/// Runs a single camera's capture loop end-to-end: acquires the camera,
+/// configures it, opens its own iceoryx2 services under `camera/<index>/...`,
+/// and publishes frames until the capture loop errors out. Each camera gets
+/// its own `CameraManager`, allocator, and request queue, so pipelines for
+/// different cameras (run concurrently via `--all`) never share state.
+fn run_camera_pipeline(camera_index: usize, width: u32, height: u32, zero_copy: bool) -> Result<(), Error> {
+    info!(camera_index, width, height, zero_copy, "Starting camera publisher");
 
     let node = NodeBuilder::new()
         .create::<ipc::Service>()
         .map_err(|e| Error::Ipc(format!("{e:?}")))?;
 
     let service = node
-        .service_builder(&SERVICE_NAME.try_into().unwrap())
+        .service_builder(&service_name(camera_index, "frames").try_into().unwrap())
         .publish_subscribe::<Frame>()
         .open_or_create()
         .map_err(|e| Error::Ipc(format!("{e:?}")))?;
@@ -55,13 +187,77 @@ fn main() -> Result<(), Error> {
         .create()
         .map_err(|e| Error::Ipc(format!("{e:?}")))?;
 
-    info!("IPC publisher ready");
+    info!(camera_index, "IPC publisher ready");
+
+    // In zero-copy mode we publish a slim FrameRef in place of the copied
+    // Frame (the `frames` service then carries nothing), alongside the
+    // dmabuf fd, which rides the companion SCM_RIGHTS socket instead of
+    // iceoryx2 shared memory (fds can't live in a shm payload).
+    let ref_publisher = if zero_copy {
+        let ref_service = node
+            .service_builder(&service_name(camera_index, "frames_ref").try_into().unwrap())
+            .publish_subscribe::<FrameRef>()
+            .open_or_create()
+            .map_err(|e| Error::Ipc(format!("{e:?}")))?;
+        Some(
+            ref_service
+                .publisher_builder()
+                .create()
+                .map_err(|e| Error::Ipc(format!("{e:?}")))?,
+        )
+    } else {
+        None
+    };
+
+    let controls_service = node
+        .service_builder(&service_name(camera_index, "controls").try_into().unwrap())
+        .publish_subscribe::<ControlUpdate>()
+        .open_or_create()
+        .map_err(|e| Error::Ipc(format!("{e:?}")))?;
+
+    let controls_subscriber = controls_service
+        .subscriber_builder()
+        .create()
+        .map_err(|e| Error::Ipc(format!("{e:?}")))?;
+
+    let mut delayed_controls = DelayedControls::new(control_latencies());
+
+    let metadata_service = node
+        .service_builder(&service_name(camera_index, "metadata").try_into().unwrap())
+        .publish_subscribe::<FrameMetadata>()
+        .open_or_create()
+        .map_err(|e| Error::Ipc(format!("{e:?}")))?;
+
+    let metadata_publisher = metadata_service
+        .publisher_builder()
+        .create()
+        .map_err(|e| Error::Ipc(format!("{e:?}")))?;
+
+    let dmabuf_clients: Arc<Mutex<Vec<UnixStream>>> = Arc::new(Mutex::new(Vec::new()));
+    let dmabuf_path = dmabuf_socket_path(camera_index);
+    if zero_copy {
+        let listener = dmabuf::bind_listener(&dmabuf_path)
+            .map_err(|e| Error::Ipc(format!("dmabuf socket bind failed: {e}")))?;
+        let clients = Arc::clone(&dmabuf_clients);
+        std::thread::spawn(move || {
+            for conn in listener.incoming().flatten() {
+                info!(camera_index, "dmabuf consumer connected");
+                // Acks are waited for with a bound (see DMABUF_ACK_TIMEOUT)
+                // so a stalled or dead consumer can't hang the capture loop.
+                if let Err(e) = conn.set_read_timeout(Some(DMABUF_ACK_TIMEOUT)) {
+                    warn!(camera_index, "failed to set dmabuf consumer read timeout: {e}");
+                }
+                clients.lock().unwrap().push(conn);
+            }
+        });
+        info!(camera_index, path = %dmabuf_path, "dmabuf fd socket listening");
+    }
 
     let mgr = CameraManager::new().map_err(|e| Error::Camera(format!("{e:?}")))?;
     let cameras = mgr.cameras();
-    let cam = cameras.get(0).ok_or(Error::NoCamera)?;
+    let cam = cameras.get(camera_index).ok_or(Error::NoCamera)?;
 
-    info!(id = %cam.id(), "Found camera");
+    info!(camera_index, id = %cam.id(), "Found camera");
 
     let mut cam = cam.acquire().map_err(|e| Error::Camera(format!("{e:?}")))?;
     let mut config = cam
@@ -129,6 +325,7 @@ fn main() -> Result<(), Error> {
     cam.start(None)
         .map_err(|e| Error::Camera(format!("{e:?}")))?;
 
+    let initial_request_count = requests.len() as u64;
     for req in requests {
         cam.queue_request(req)
             .map_err(|(_, e)| Error::Camera(format!("{e:?}")))?;
@@ -137,6 +334,9 @@ fn main() -> Result<(), Error> {
     info!("Capture loop starting");
 
     let mut seq: u64 = 0;
+    // libcamera assigns its own per-request sequence number starting at 0;
+    // the requests already queued above consumed 0..initial_request_count.
+    let mut request_seq: u64 = initial_request_count;
 
     loop {
         // block on receive camera capture request
@@ -159,9 +359,108 @@ fn main() -> Result<(), Error> {
         };
 
         let ts = metadata.timestamp();
+        let frame_seq = metadata.sequence();
         let planes = fb.data();
         let plane_metadata = metadata.planes();
 
+        if zero_copy {
+            // No memcpy: hand out the dmabuf fds backing this FrameBuffer's
+            // planes and publish only their layout. The receiving end mmaps
+            // the fd itself (delivered over the companion socket) instead of
+            // reading a copied payload.
+            let mut plane_offsets = [0u32; MAX_PLANES];
+            let mut plane_lens = [0u32; MAX_PLANES];
+            let mut plane_count = 0u32;
+            let dmabuf_fds: Vec<_> = fb.planes().iter().map(|p| p.fd().as_raw_fd()).collect();
+
+            // Each plane's offset is its own real position within the dmabuf
+            // it belongs to (not a cumulative offset across planes): planes
+            // may share one dmabuf at different offsets, or each live in its
+            // own dmabuf at offset 0, depending on the platform's allocator.
+            for (i, (plane_data, fb_plane)) in planes.iter().zip(fb.planes().iter()).enumerate().take(MAX_PLANES) {
+                let bytes_used = plane_metadata
+                    .get(i)
+                    .map(|p| p.bytes_used)
+                    .unwrap_or(plane_data.len() as u32);
+                plane_offsets[i] = fb_plane.offset();
+                plane_lens[i] = bytes_used;
+                plane_count += 1;
+            }
+
+            {
+                let mut clients = dmabuf_clients.lock().unwrap();
+                clients.retain_mut(|client| match dmabuf::send_fds(client, seq, &dmabuf_fds) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!(camera_index, "dmabuf fd send failed, dropping consumer: {e}");
+                        false
+                    }
+                });
+            }
+
+            if let Some(ref_publisher) = &ref_publisher {
+                match ref_publisher.loan_uninit() {
+                    Ok(sample) => {
+                        let sample = sample.write_payload(FrameRef {
+                            timestamp_ns: ts,
+                            sequence: seq,
+                            width: actual_width,
+                            height: actual_height,
+                            stride,
+                            format: pixel_format,
+                            plane_count,
+                            plane_offsets,
+                            plane_lens,
+                        });
+                        let _ = sample.send();
+                        debug!(seq, planes = plane_count, "Published FrameRef (zero-copy)");
+                    }
+                    Err(e) => warn!("FrameRef loan failed: {e:?}"),
+                }
+            }
+
+            publish_frame_metadata(&metadata_publisher, &mut delayed_controls, &req, seq, ts, frame_seq);
+
+            seq += 1;
+            if seq % 100 == 0 {
+                info!(seq, "Progress (zero-copy)");
+            }
+
+            apply_delayed_controls(&mut delayed_controls, &controls_subscriber, &mut req, request_seq)?;
+            request_seq += 1;
+
+            // Nothing should requeue this dmabuf with libcamera while a
+            // zero-copy consumer might still be mmap'ing it, or the next
+            // capture tears the frame mid-read. Every currently-connected
+            // consumer must ack the sequence just sent (bounded by
+            // DMABUF_ACK_TIMEOUT) before the buffer goes back to libcamera;
+            // one that doesn't is dropped rather than stalling the others.
+            {
+                let mut clients = dmabuf_clients.lock().unwrap();
+                let sent_seq = seq - 1;
+                clients.retain_mut(|client| match dmabuf::recv_ack(client) {
+                    Ok(Some(acked)) if acked == sent_seq => true,
+                    Ok(Some(acked)) => {
+                        warn!(camera_index, acked, expected = sent_seq, "dmabuf consumer acked the wrong frame, dropping");
+                        false
+                    }
+                    Ok(None) => {
+                        info!(camera_index, "dmabuf consumer disconnected");
+                        false
+                    }
+                    Err(e) => {
+                        warn!(camera_index, "dmabuf consumer did not ack in time, dropping: {e}");
+                        false
+                    }
+                });
+            }
+
+            req.reuse(ReuseFlag::REUSE_BUFFERS);
+            cam.queue_request(req)
+                .map_err(|(_, e)| Error::Camera(format!("{e:?}")))?;
+            continue;
+        }
+
         // Collect all plane data into contiguous buffer
         let mut frame_data = [0u8; MAX_FRAME_SIZE];
         let mut offset = 0usize;
@@ -202,14 +501,116 @@ fn main() -> Result<(), Error> {
                 }
             }
 
+            publish_frame_metadata(&metadata_publisher, &mut delayed_controls, &req, seq, ts, frame_seq);
+
             seq += 1;
             if seq % 100 == 0 {
                 info!(seq, planes = planes.len(), "Progress");
             }
         }
 
+        apply_delayed_controls(&mut delayed_controls, &controls_subscriber, &mut req, request_seq)?;
+        request_seq += 1;
+
         req.reuse(ReuseFlag::REUSE_BUFFERS);
         cam.queue_request(req)
             .map_err(|(_, e)| Error::Camera(format!("{e:?}")))?;
     }
 }
+
+/// Drains pending updates from `camera/<index>/controls`, folds them into
+/// `delayed`, and writes whatever values are now due into the request about
+/// to be queued for `next_sequence`. Also arbitrates auto vs. manual
+/// exposure: AE is disabled as soon as a manual control has ever been
+/// pushed, since libcamera would otherwise silently override it.
+fn apply_delayed_controls<S: iceoryx2::service::Service>(
+    delayed: &mut DelayedControls,
+    subscriber: &iceoryx2::port::subscriber::Subscriber<S, ControlUpdate, ()>,
+    req: &mut libcamera::request::Request,
+    next_sequence: u64,
+) -> Result<(), Error> {
+    let mut updates = Vec::new();
+    while let Ok(Some(sample)) = subscriber.receive() {
+        let payload = sample.payload();
+        if let Some(id) = ControlId::from_wire(payload.control_id) {
+            let value = match id {
+                // Per ControlUpdate's doc comment, an integer control's
+                // value rides in the f32's bit pattern, not its numeric
+                // value, so this reinterprets rather than casts.
+                ControlId::ExposureTime => ControlValue::ExposureTime(payload.value.to_bits() as i32),
+                ControlId::AnalogueGain => ControlValue::AnalogueGain(payload.value),
+            };
+            updates.push(value);
+        }
+    }
+    delayed.push(updates);
+
+    // libcamera's AGC/AE overrides manually-written ExposureTime/AnalogueGain
+    // whenever AeEnable is true, so a manual update is a no-op until AE is
+    // switched off. Once any control has been pushed over `camera/<index>/controls`,
+    // keep AE disabled for the life of the pipeline so the manual values we
+    // write below actually take effect.
+    req.controls_mut()
+        .set(AeEnable(!delayed.has_manual_controls()))
+        .map_err(|e| Error::Camera(format!("{e:?}")))?;
+
+    for value in delayed.controls_for_request(next_sequence) {
+        let result = match value {
+            ControlValue::ExposureTime(v) => req.controls_mut().set(ExposureTime(v)),
+            ControlValue::AnalogueGain(v) => req.controls_mut().set(AnalogueGain(v)),
+        };
+        result.map_err(|e| Error::Camera(format!("{e:?}")))?;
+    }
+    Ok(())
+}
+
+/// Builds and publishes the `camera/<index>/metadata` sample for a completed frame,
+/// tagged with the same `sequence` as its image `Frame`/`FrameRef` so the
+/// two can be correlated downstream. Exposure/gain come from
+/// [`DelayedControls`] so they reflect what was actually in effect for
+/// `frame_seq`; white-balance and digital gain are read from the request's
+/// completed-capture metadata (`req.metadata()`), not its input control
+/// list — the input list only ever holds what a client wrote before the
+/// request was queued, never what the ISP/AGC actually produced.
+fn publish_frame_metadata<S: iceoryx2::service::Service>(
+    publisher: &iceoryx2::port::publisher::Publisher<S, FrameMetadata, ()>,
+    delayed: &mut DelayedControls,
+    req: &libcamera::request::Request,
+    seq: u64,
+    timestamp_ns: u64,
+    frame_seq: u32,
+) {
+    let exposure_time_us = match delayed.applied_at(ControlId::ExposureTime, frame_seq as u64) {
+        Some(ControlValue::ExposureTime(v)) => v as u32,
+        _ => 0,
+    };
+    let analogue_gain = match delayed.applied_at(ControlId::AnalogueGain, frame_seq as u64) {
+        Some(ControlValue::AnalogueGain(v)) => v,
+        _ => 0.0,
+    };
+
+    let digital_gain = req.metadata().get::<DigitalGain>().map(|g| g.0).unwrap_or(1.0);
+    let (wb_red_gain, wb_blue_gain) = req
+        .metadata()
+        .get::<ColourGains>()
+        .map(|g| (g.0[0], g.0[1]))
+        .unwrap_or((1.0, 1.0));
+    let ae_locked = req.metadata().get::<AeLocked>().map(|l| l.0).unwrap_or(false);
+
+    match publisher.loan_uninit() {
+        Ok(sample) => {
+            let sample = sample.write_payload(FrameMetadata {
+                sequence: seq,
+                timestamp_ns,
+                exposure_time_us,
+                analogue_gain,
+                digital_gain,
+                wb_red_gain,
+                wb_blue_gain,
+                ae_locked: ae_locked as u8,
+            });
+            let _ = sample.send();
+        }
+        Err(e) => warn!("FrameMetadata loan failed: {e:?}"),
+    }
+}
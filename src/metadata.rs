@@ -0,0 +1,22 @@
+use iceoryx2::prelude::ZeroCopySend;
+
+/// Per-frame statistics sidecar, published on `camera/<index>/metadata` with the
+/// same `sequence` as the matching image [`crate::frame::Frame`] /
+/// [`crate::frame::FrameRef`], so consumers can correlate the two without
+/// bloating the image sample itself.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ZeroCopySend)]
+#[type_name("FrameMetadata")]
+pub struct FrameMetadata {
+    pub sequence: u64,
+    pub timestamp_ns: u64,
+    pub exposure_time_us: u32,
+    pub analogue_gain: f32,
+    pub digital_gain: f32,
+    pub wb_red_gain: f32,
+    pub wb_blue_gain: f32,
+    pub ae_locked: u8,
+}
+
+unsafe impl Send for FrameMetadata {}
+unsafe impl Sync for FrameMetadata {}
@@ -0,0 +1,171 @@
+//! Minimal V4L2 output-mode ioctl bindings.
+//!
+//! Just enough of the V4L2 API to drive a v4l2loopback device as an output:
+//! `S_FMT`, `REQBUFS`, `QBUF`/`DQBUF` with a single mmap buffer. Not a
+//! general-purpose V4L2 binding — callers needing capture-side ioctls or
+//! multi-buffer queues should reach for a real `v4l` crate instead.
+
+use std::os::fd::RawFd;
+
+pub const V4L2_BUF_TYPE_VIDEO_OUTPUT: u32 = 2;
+pub const V4L2_MEMORY_MMAP: u32 = 1;
+pub const V4L2_FIELD_NONE: u32 = 1;
+
+pub const V4L2_PIX_FMT_YUV420: u32 = fourcc(b"YU12");
+pub const V4L2_PIX_FMT_NV12: u32 = fourcc(b"NV12");
+pub const V4L2_PIX_FMT_NV21: u32 = fourcc(b"NV21");
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    (code[0] as u32) | (code[1] as u32) << 8 | (code[2] as u32) << 16 | (code[3] as u32) << 24
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct V4l2PixFormat {
+    pub width: u32,
+    pub height: u32,
+    pub pixelformat: u32,
+    pub field: u32,
+    pub bytesperline: u32,
+    pub sizeimage: u32,
+    pub colorspace: u32,
+    pub priv_: u32,
+    pub flags: u32,
+    pub ycbcr_enc: u32,
+    pub quantization: u32,
+    pub xfer_func: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct V4l2Format {
+    pub type_: u32,
+    pub pix: V4l2PixFormat,
+    // Real v4l2_format is a union over `fmt`, sized to the 200-byte
+    // `raw_data` member; output-mode only ever uses `pix` (48 bytes), so the
+    // remaining 152 bytes other variants would use are tracked as padding.
+    _reserved: [u8; 152],
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct V4l2RequestBuffers {
+    pub count: u32,
+    pub type_: u32,
+    pub memory: u32,
+    _reserved: [u32; 2],
+}
+
+/// Mirrors the kernel's `struct v4l2_timecode`, embedded in `v4l2_buffer`
+/// between `timestamp` and `sequence`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct V4l2Timecode {
+    pub type_: u32,
+    pub flags: u32,
+    pub frames: u8,
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub userbits: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct V4l2Buffer {
+    pub index: u32,
+    pub type_: u32,
+    pub bytesused: u32,
+    pub flags: u32,
+    pub field: u32,
+    pub timestamp: [i64; 2],
+    pub timecode: V4l2Timecode,
+    pub sequence: u32,
+    pub memory: u32,
+    // Real v4l2_buffer has a union `m` here (offset/userptr/planes/fd);
+    // MMAP memory only ever needs `offset`, but the union is pointer-sized
+    // on 64-bit, so the field is kept at full width to preserve layout.
+    m: u64,
+    pub length: u32,
+    reserved2: u32,
+    request_fd: i32,
+}
+
+impl V4l2Buffer {
+    /// The `m.offset` member, valid when `memory == V4L2_MEMORY_MMAP`.
+    pub fn mmap_offset(&self) -> u32 {
+        self.m as u32
+    }
+}
+
+nix::ioctl_readwrite!(s_fmt, b'V', 5, V4l2Format);
+nix::ioctl_readwrite!(reqbufs, b'V', 8, V4l2RequestBuffers);
+nix::ioctl_readwrite!(querybuf, b'V', 9, V4l2Buffer);
+nix::ioctl_readwrite!(qbuf, b'V', 15, V4l2Buffer);
+nix::ioctl_readwrite!(dqbuf, b'V', 17, V4l2Buffer);
+nix::ioctl_write_ptr!(streamon, b'V', 18, u32);
+nix::ioctl_write_ptr!(streamoff, b'V', 19, u32);
+
+pub fn set_format(fd: RawFd, width: u32, height: u32, stride: u32, pixelformat: u32) -> nix::Result<V4l2Format> {
+    let mut fmt = V4l2Format {
+        type_: V4L2_BUF_TYPE_VIDEO_OUTPUT,
+        pix: V4l2PixFormat {
+            width,
+            height,
+            pixelformat,
+            field: V4L2_FIELD_NONE,
+            bytesperline: stride,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    unsafe { s_fmt(fd, &mut fmt)? };
+    Ok(fmt)
+}
+
+pub fn request_one_buffer(fd: RawFd) -> nix::Result<()> {
+    let mut req = V4l2RequestBuffers {
+        count: 1,
+        type_: V4L2_BUF_TYPE_VIDEO_OUTPUT,
+        memory: V4L2_MEMORY_MMAP,
+        ..Default::default()
+    };
+    unsafe { reqbufs(fd, &mut req)? };
+    Ok(())
+}
+
+pub fn query_buffer(fd: RawFd, index: u32) -> nix::Result<V4l2Buffer> {
+    let mut buf = V4l2Buffer {
+        index,
+        type_: V4L2_BUF_TYPE_VIDEO_OUTPUT,
+        memory: V4L2_MEMORY_MMAP,
+        ..Default::default()
+    };
+    unsafe { querybuf(fd, &mut buf)? };
+    Ok(buf)
+}
+
+pub fn queue_and_dequeue(fd: RawFd, index: u32, bytesused: u32) -> nix::Result<()> {
+    let mut buf = V4l2Buffer {
+        index,
+        type_: V4L2_BUF_TYPE_VIDEO_OUTPUT,
+        memory: V4L2_MEMORY_MMAP,
+        bytesused,
+        ..Default::default()
+    };
+    unsafe { qbuf(fd, &mut buf)? };
+    unsafe { dqbuf(fd, &mut buf)? };
+    Ok(())
+}
+
+pub fn stream_on(fd: RawFd) -> nix::Result<()> {
+    let type_ = V4L2_BUF_TYPE_VIDEO_OUTPUT;
+    unsafe { streamon(fd, &type_)? };
+    Ok(())
+}
+
+pub fn stream_off(fd: RawFd) -> nix::Result<()> {
+    let type_ = V4L2_BUF_TYPE_VIDEO_OUTPUT;
+    unsafe { streamoff(fd, &type_)? };
+    Ok(())
+}